@@ -0,0 +1,190 @@
+//! Thin abstraction over rumqttc's v4 and v5 client/eventloop pairs so the rest of the
+//! bridge can forward messages without caring which protocol version a given broker
+//! speaks. v5-specific data (message properties) is carried as `ForwardProperties` and
+//! simply absent when running a broker in v4 mode.
+
+use anyhow::{Context, Result};
+use mr_bridge::ForwardProperties;
+use rumqttc::v5::mqttbytes::v5::{Publish as PublishV5, PublishProperties};
+use rumqttc::v5::{
+    AsyncClient as AsyncClientV5, Event as EventV5, EventLoop as EventLoopV5,
+    MqttOptions as MqttOptionsV5, Packet as PacketV5,
+};
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, Publish, QoS};
+use std::collections::HashMap;
+
+pub enum ClientHandle {
+    V4(AsyncClient),
+    V5(AsyncClientV5),
+}
+
+impl ClientHandle {
+    pub async fn subscribe(&self, topic: &str, qos: QoS) -> Result<()> {
+        match self {
+            ClientHandle::V4(client) => client.subscribe(topic, qos).await?,
+            ClientHandle::V5(client) => client.subscribe(topic, qos).await?,
+        }
+        Ok(())
+    }
+
+    pub async fn unsubscribe(&self, topic: &str) -> Result<()> {
+        match self {
+            ClientHandle::V4(client) => client.unsubscribe(topic).await?,
+            ClientHandle::V5(client) => client.unsubscribe(topic).await?,
+        }
+        Ok(())
+    }
+
+    pub async fn publish(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: Vec<u8>,
+        properties: Option<&ForwardProperties>,
+    ) -> Result<()> {
+        match self {
+            ClientHandle::V4(client) => {
+                client.publish(topic, qos, retain, payload).await?;
+            }
+            ClientHandle::V5(client) => {
+                if let Some(props) = properties {
+                    client
+                        .publish_with_properties(topic, qos, retain, payload, to_v5_properties(props))
+                        .await?;
+                } else {
+                    client.publish(topic, qos, retain, payload).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub enum EventLoopHandle {
+    V4(EventLoop),
+    /// The broker-assigned topic-alias table (alias -> full topic) is per-connection, so
+    /// it lives alongside the eventloop and is rebuilt whenever the connection is.
+    V5(EventLoopV5, HashMap<u16, String>),
+}
+
+/// A protocol-version-agnostic view of the events the bridge cares about.
+pub enum BridgeEvent {
+    ConnAck,
+    Publish(NormalizedPublish),
+    Other,
+}
+
+pub struct NormalizedPublish {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: QoS,
+    pub retain: bool,
+    pub properties: Option<ForwardProperties>,
+}
+
+impl EventLoopHandle {
+    /// Poll the underlying eventloop once, normalizing the result. Connection errors are
+    /// surfaced as `Err` just like the raw eventloops do, so callers keep their existing
+    /// backoff-and-retry behavior.
+    pub async fn poll(&mut self) -> Result<BridgeEvent> {
+        match self {
+            EventLoopHandle::V4(eventloop) => match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    Ok(BridgeEvent::Publish(normalize_v4(publish)))
+                }
+                Ok(Event::Incoming(Packet::ConnAck(_))) => Ok(BridgeEvent::ConnAck),
+                Ok(_) => Ok(BridgeEvent::Other),
+                Err(e) => Err(e).context("v4 eventloop error"),
+            },
+            EventLoopHandle::V5(eventloop, aliases) => match eventloop.poll().await {
+                Ok(EventV5::Incoming(PacketV5::Publish(publish))) => {
+                    Ok(BridgeEvent::Publish(normalize_v5(publish, aliases)))
+                }
+                Ok(EventV5::Incoming(PacketV5::ConnAck(_))) => {
+                    // The broker forgets any aliases it previously assigned us once the
+                    // connection drops, so a reconnect must start from a clean table too.
+                    aliases.clear();
+                    Ok(BridgeEvent::ConnAck)
+                }
+                Ok(_) => Ok(BridgeEvent::Other),
+                Err(e) => Err(e).context("v5 eventloop error"),
+            },
+        }
+    }
+}
+
+fn normalize_v4(publish: Publish) -> NormalizedPublish {
+    NormalizedPublish {
+        topic: publish.topic,
+        payload: publish.payload.to_vec(),
+        qos: publish.qos,
+        retain: publish.retain,
+        properties: None,
+    }
+}
+
+/// Normalize an inbound v5 publish, resolving topic aliases. A broker may send an
+/// aliased publish with an empty topic and `properties.topic_alias` set, meaning "this is
+/// the same topic as the last publish that carried alias N" — we have to remember that
+/// mapping ourselves and substitute the real topic back in, or the message matches no rule.
+fn normalize_v5(publish: PublishV5, aliases: &mut HashMap<u16, String>) -> NormalizedPublish {
+    let topic_alias = publish
+        .properties
+        .as_ref()
+        .and_then(|props| props.topic_alias);
+
+    let topic = if publish.topic.is_empty() {
+        topic_alias
+            .and_then(|alias| aliases.get(&alias).cloned())
+            .unwrap_or_default()
+    } else {
+        let topic = String::from_utf8_lossy(&publish.topic).into_owned();
+        if let Some(alias) = topic_alias {
+            aliases.insert(alias, topic.clone());
+        }
+        topic
+    };
+
+    let properties = publish.properties.as_ref().map(|props| ForwardProperties {
+        message_expiry_interval: props.message_expiry_interval,
+        content_type: props.content_type.clone(),
+        response_topic: props.response_topic.clone(),
+        correlation_data: props.correlation_data.as_ref().map(|d| d.to_vec()),
+        user_properties: props.user_properties.clone(),
+    });
+
+    NormalizedPublish {
+        topic,
+        payload: publish.payload.to_vec(),
+        qos: publish.qos,
+        retain: publish.retain,
+        properties,
+    }
+}
+
+fn to_v5_properties(props: &ForwardProperties) -> PublishProperties {
+    PublishProperties {
+        payload_format_indicator: None,
+        message_expiry_interval: props.message_expiry_interval,
+        topic_alias: None,
+        response_topic: props.response_topic.clone(),
+        correlation_data: props.correlation_data.clone().map(Into::into),
+        user_properties: props.user_properties.clone(),
+        subscription_identifiers: Vec::new(),
+        content_type: props.content_type.clone(),
+    }
+}
+
+pub fn new_v4(mqttoptions: MqttOptions) -> (ClientHandle, EventLoopHandle) {
+    let (client, eventloop) = AsyncClient::new(mqttoptions, 100);
+    (ClientHandle::V4(client), EventLoopHandle::V4(eventloop))
+}
+
+pub fn new_v5(mqttoptions: MqttOptionsV5) -> (ClientHandle, EventLoopHandle) {
+    let (client, eventloop) = AsyncClientV5::new(mqttoptions, 100);
+    (
+        ClientHandle::V5(client),
+        EventLoopHandle::V5(eventloop, HashMap::new()),
+    )
+}
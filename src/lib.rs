@@ -12,6 +12,32 @@ pub struct MqttBrokerConfig {
     pub password: Option<String>,
     #[serde(default = "default_client_id")]
     pub client_id: String,
+    /// TLS / mutual-TLS settings. Omit for a plaintext connection.
+    pub tls: Option<TlsConfig>,
+    /// MQTT protocol version to speak to this broker.
+    #[serde(default)]
+    pub protocol: MqttProtocol,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttProtocol {
+    #[default]
+    V4,
+    V5,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Path to a PEM-encoded CA certificate used to verify the broker.
+    pub ca_cert: std::path::PathBuf,
+    /// Path to a PEM-encoded client certificate, for mutual TLS.
+    pub client_cert: Option<std::path::PathBuf>,
+    /// Path to the PEM-encoded private key matching `client_cert`.
+    pub client_key: Option<std::path::PathBuf>,
+    /// Skip verifying the broker's certificate chain/hostname. Only ever use this for testing.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
 }
 
 fn default_mqtt_port() -> u16 {
@@ -32,8 +58,11 @@ pub enum Direction {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BridgeRule {
-    /// Supports MQTT wildcards (+ for single level, # for multi-level)
-    pub topic: String,
+    /// One or more topic filters sharing this rule's direction/QoS/remap. Supports
+    /// MQTT wildcards (+ for single level, # for multi-level). Accepts either a single
+    /// string or a list, e.g. `["home/+/temp", "home/+/humidity"]`.
+    #[serde(deserialize_with = "deserialize_topic_filters")]
+    pub topic: Vec<String>,
     /// Which direction we're forwarding messages
     pub direction: Direction,
     /// Log every message that matches the topic we're bridging
@@ -42,12 +71,37 @@ pub struct BridgeRule {
     /// Quality of Service level (0, 1, or 2)
     #[serde(default = "default_qos")]
     pub qos: u8,
+    /// Rewrite the topic on the destination broker. Uses the same `+`/`#` wildcard
+    /// characters as `topic`; each placeholder is filled in, in order, from the
+    /// segment(s) the subscription wildcard captured. For example a rule with
+    /// `topic = "sensors/+/temp"` and `remap = "upstream/sensors/+/temp"` forwards
+    /// `sensors/living/temp` as `upstream/sensors/living/temp`. When absent the
+    /// topic is mirrored verbatim.
+    pub remap: Option<String>,
 }
 
 fn default_qos() -> u8 {
     0
 }
 
+/// Accept either a single topic filter string or a list of filters for `BridgeRule::topic`.
+fn deserialize_topic_filters<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(topic) => Ok(vec![topic]),
+        OneOrMany::Many(topics) => Ok(topics),
+    }
+}
+
 impl BridgeRule {
     pub fn qos(&self) -> QoS {
         match self.qos {
@@ -59,11 +113,74 @@ impl BridgeRule {
     }
 }
 
+/// Request/response correlation bridging for the common "device listens on a command
+/// topic, answers on a response topic" RPC pattern. The bridge forwards each command
+/// across once, tracking it as in-flight until the matching response comes back (or
+/// `timeout_secs` elapses), so a duplicate request doesn't get forwarded twice while
+/// the first is still outstanding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcRule {
+    /// Filter devices subscribe to for incoming commands, e.g. `devices/+/command/#`.
+    pub command_topic: String,
+    /// Filter devices publish their answers on, e.g. `devices/+/response/#`.
+    pub response_topic: String,
+    /// Which broker the command originates on (NearToFar: near issues commands, far
+    /// hosts the device; FarToNear: the mirror image). `Wherever` is not supported.
+    pub direction: Direction,
+    /// Zero-indexed `/`-separated topic segment to use as the correlation key when a
+    /// message carries no v5 correlation-data (e.g. plain v4 traffic).
+    pub request_id_segment: Option<usize>,
+    /// How long a request is remembered as in-flight before it's assumed abandoned.
+    #[serde(default = "default_rpc_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Quality of Service level (0, 1, or 2) used for both legs of the exchange.
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+}
+
+fn default_rpc_timeout_secs() -> u64 {
+    30
+}
+
+impl RpcRule {
+    pub fn qos(&self) -> QoS {
+        match self.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            _ => QoS::AtMostOnce,
+        }
+    }
+}
+
+/// MQTT v5 message properties worth carrying across the bridge verbatim, so a v5
+/// request/response exchange isn't mangled by passing through mr-bridge. Not used
+/// in v4 mode, where the protocol has no concept of message properties.
+#[derive(Debug, Clone, Default)]
+pub struct ForwardProperties {
+    pub message_expiry_interval: Option<u32>,
+    pub content_type: Option<String>,
+    pub response_topic: Option<String>,
+    pub correlation_data: Option<Vec<u8>>,
+    pub user_properties: Vec<(String, String)>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BridgeConfig {
     pub near: MqttBrokerConfig,
     pub far: MqttBrokerConfig,
     pub rules: Vec<BridgeRule>,
+    /// Request/response RPC bridging rules, separate from the plain one-way `rules`.
+    #[serde(default)]
+    pub rpc_rules: Vec<RpcRule>,
+    /// Prometheus metrics endpoint. Omit to run without one.
+    pub metrics: Option<MetricsConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Address the metrics HTTP server listens on, e.g. `"0.0.0.0:9100"`.
+    pub listen: std::net::SocketAddr,
 }
 
 impl BridgeConfig {
@@ -72,7 +189,7 @@ impl BridgeConfig {
         let content = std::fs::read_to_string(path.as_ref())?;
         let ext = path.as_ref().extension().and_then(|s| s.to_str());
 
-        let config = match ext {
+        let config: BridgeConfig = match ext {
             Some("toml") => toml::from_str(&content)?,
             Some("json") => serde_json::from_str(&content)?,
             _ => {
@@ -81,8 +198,45 @@ impl BridgeConfig {
             }
         };
 
+        config.validate_topic_filters()?;
+
         Ok(config)
     }
+
+    /// Reject rules whose topic filters aren't valid MQTT subscription filters (e.g.
+    /// `a/#/b` or `a/+b`), so a typo surfaces at startup/reload instead of silently
+    /// producing a rule that never matches anything.
+    fn validate_topic_filters(&self) -> anyhow::Result<()> {
+        for (i, rule) in self.rules.iter().enumerate() {
+            for filter in &rule.topic {
+                if !rumqttc::valid_filter(filter) {
+                    anyhow::bail!("rule #{} has an invalid topic filter: {:?}", i, filter);
+                }
+            }
+        }
+
+        for (i, rule) in self.rpc_rules.iter().enumerate() {
+            if !rumqttc::valid_filter(&rule.command_topic) {
+                anyhow::bail!(
+                    "rpc_rules #{} has an invalid command_topic filter: {:?}",
+                    i,
+                    rule.command_topic
+                );
+            }
+            if !rumqttc::valid_filter(&rule.response_topic) {
+                anyhow::bail!(
+                    "rpc_rules #{} has an invalid response_topic filter: {:?}",
+                    i,
+                    rule.response_topic
+                );
+            }
+            if matches!(rule.direction, Direction::Wherever) {
+                anyhow::bail!("rpc_rules #{} cannot use direction 'wherever'", i);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Parser, Debug)]
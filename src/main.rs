@@ -1,21 +1,32 @@
-use anyhow::{Context, Result};
+mod broker;
+mod metrics;
+
+use anyhow::{bail, Context, Result};
+use broker::{BridgeEvent, ClientHandle, EventLoopHandle};
 use clap::Parser;
-use mr_bridge::{Args, BridgeConfig, Direction, MqttBrokerConfig};
-use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, Publish, QoS};
+use metrics::Metrics;
+use mr_bridge::{Args, BridgeConfig, Direction, MqttBrokerConfig, MqttProtocol, RpcRule, TlsConfig};
+use rumqttc::v5::MqttOptions as MqttOptionsV5;
+use rumqttc::{MqttOptions, QoS, TlsConfiguration, Transport};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
 struct Bridge {
-    near_client: AsyncClient,
-    near_eventloop: EventLoop,
-    far_client: AsyncClient,
-    far_eventloop: EventLoop,
+    near_client: ClientHandle,
+    near_eventloop: EventLoopHandle,
+    far_client: ClientHandle,
+    far_eventloop: EventLoopHandle,
     config: Arc<RwLock<BridgeConfig>>,
     config_path: std::path::PathBuf,
     reload_topic: Option<String>,
     reload_broker: String,
+    /// In-flight RPC requests, keyed by (index into `config.rpc_rules`, correlation key).
+    rpc_inflight: RwLock<HashMap<(usize, String), Instant>>,
+    metrics: Arc<Metrics>,
 }
 
 impl Bridge {
@@ -26,6 +37,17 @@ impl Bridge {
         let (near_client, near_eventloop) = create_mqtt_client(&config.near, "near")?;
         let (far_client, far_eventloop) = create_mqtt_client(&config.far, "far")?;
 
+        let metrics = Arc::new(Metrics::default());
+        if let Some(metrics_config) = &config.metrics {
+            let listen = metrics_config.listen;
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                if let Err(e) = metrics::serve(listen, metrics).await {
+                    error!("Metrics endpoint failed: {:#}", e);
+                }
+            });
+        }
+
         Ok(Self {
             near_client,
             near_eventloop,
@@ -35,76 +57,114 @@ impl Bridge {
             config_path: args.config.clone(),
             reload_topic: args.reload_topic.clone(),
             reload_broker: args.reload_broker.clone(),
+            rpc_inflight: RwLock::new(HashMap::new()),
+            metrics,
         })
     }
 
     async fn subscribe_to_topics(&self) -> Result<()> {
+        self.subscribe_near_topics().await?;
+        self.subscribe_far_topics().await?;
+        Ok(())
+    }
+
+    /// (Re-)issue every subscription that belongs on the NEAR broker: rules forwarding
+    /// NearToFar/Wherever, plus the reload topic if it lives there. Safe to call again
+    /// after a reconnect, since the broker forgets our subscriptions across connections.
+    async fn subscribe_near_topics(&self) -> Result<()> {
         let config = self.config.read().await;
 
         for rule in &config.rules {
             match rule.direction {
-                Direction::NearToFar => {
-                    info!(
-                        "Subscribing to '{}' on NEAR broker (forwarding to FAR)",
-                        rule.topic
-                    );
-                    self.near_client
-                        .subscribe(&rule.topic, rule.qos())
-                        .await
-                        .context(format!(
-                            "Failed to subscribe to '{}' on near broker",
-                            rule.topic
-                        ))?;
-                }
-                Direction::FarToNear => {
-                    info!(
-                        "Subscribing to '{}' on FAR broker (forwarding to NEAR)",
-                        rule.topic
-                    );
-                    self.far_client
-                        .subscribe(&rule.topic, rule.qos())
-                        .await
-                        .context(format!(
-                            "Failed to subscribe to '{}' on far broker",
-                            rule.topic
-                        ))?;
+                Direction::NearToFar | Direction::Wherever => {
+                    for filter in &rule.topic {
+                        info!(
+                            "Subscribing to '{}' on NEAR broker (forwarding to FAR)",
+                            filter
+                        );
+                        self.near_client
+                            .subscribe(filter, rule.qos())
+                            .await
+                            .context(format!(
+                                "Failed to subscribe to '{}' on near broker",
+                                filter
+                            ))?;
+                    }
                 }
-                Direction::Wherever => {
-                    info!(
-                        "Subscribing to '{}' on BOTH brokers (bidirectional)",
-                        rule.topic
-                    );
-                    self.near_client
-                        .subscribe(&rule.topic, rule.qos())
-                        .await
-                        .context(format!(
-                            "Failed to subscribe to '{}' on near broker",
-                            rule.topic
-                        ))?;
-                    self.far_client
-                        .subscribe(&rule.topic, rule.qos())
-                        .await
-                        .context(format!(
-                            "Failed to subscribe to '{}' on far broker",
-                            rule.topic
-                        ))?;
+                Direction::FarToNear => {}
+            }
+        }
+
+        for rule in &config.rpc_rules {
+            let (filter, role) = match rule.direction {
+                Direction::NearToFar => (&rule.command_topic, "command"),
+                Direction::FarToNear => (&rule.response_topic, "response"),
+                Direction::Wherever => continue,
+            };
+            info!("Subscribing to RPC {} topic '{}' on NEAR broker", role, filter);
+            self.near_client
+                .subscribe(filter, rule.qos())
+                .await
+                .context(format!("Failed to subscribe to RPC {} topic on near broker", role))?;
+        }
+
+        if let Some(reload_topic) = &self.reload_topic {
+            if self.reload_broker == "near" {
+                info!(
+                    "Subscribing to reload topic '{}' on NEAR broker",
+                    reload_topic
+                );
+                self.near_client
+                    .subscribe(reload_topic, QoS::AtLeastOnce)
+                    .await
+                    .context("Failed to subscribe to reload topic")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// (Re-)issue every subscription that belongs on the FAR broker. See
+    /// `subscribe_near_topics` for when this needs to be called again.
+    async fn subscribe_far_topics(&self) -> Result<()> {
+        let config = self.config.read().await;
+
+        for rule in &config.rules {
+            match rule.direction {
+                Direction::FarToNear | Direction::Wherever => {
+                    for filter in &rule.topic {
+                        info!(
+                            "Subscribing to '{}' on FAR broker (forwarding to NEAR)",
+                            filter
+                        );
+                        self.far_client
+                            .subscribe(filter, rule.qos())
+                            .await
+                            .context(format!(
+                                "Failed to subscribe to '{}' on far broker",
+                                filter
+                            ))?;
+                    }
                 }
+                Direction::NearToFar => {}
             }
         }
 
-        // Subscribe to reload topic if configured
+        for rule in &config.rpc_rules {
+            let (filter, role) = match rule.direction {
+                Direction::NearToFar => (&rule.response_topic, "response"),
+                Direction::FarToNear => (&rule.command_topic, "command"),
+                Direction::Wherever => continue,
+            };
+            info!("Subscribing to RPC {} topic '{}' on FAR broker", role, filter);
+            self.far_client
+                .subscribe(filter, rule.qos())
+                .await
+                .context(format!("Failed to subscribe to RPC {} topic on far broker", role))?;
+        }
+
         if let Some(reload_topic) = &self.reload_topic {
             match self.reload_broker.as_str() {
-                "near" => {
-                    info!(
-                        "Subscribing to reload topic '{}' on NEAR broker",
-                        reload_topic
-                    );
-                    self.near_client
-                        .subscribe(reload_topic, QoS::AtLeastOnce)
-                        .await
-                        .context("Failed to subscribe to reload topic")?;
-                }
                 "far" => {
                     info!(
                         "Subscribing to reload topic '{}' on FAR broker",
@@ -115,6 +175,7 @@ impl Bridge {
                         .await
                         .context("Failed to subscribe to reload topic")?;
                 }
+                "near" => {}
                 _ => warn!("Invalid reload_broker value: {}", self.reload_broker),
             }
         }
@@ -131,35 +192,52 @@ impl Bridge {
         // Unsubscribe from old topics
         let old_config = self.config.read().await;
         for rule in &old_config.rules {
-            match rule.direction {
-                Direction::NearToFar => {
-                    debug!("Unsubscribing from '{}' on NEAR broker", rule.topic);
-                    let _ = self.near_client.unsubscribe(&rule.topic).await;
-                }
-                Direction::FarToNear => {
-                    debug!("Unsubscribing from '{}' on FAR broker", rule.topic);
-                    let _ = self.far_client.unsubscribe(&rule.topic).await;
-                }
-                Direction::Wherever => {
-                    debug!("Unsubscribing from '{}' on BOTH brokers", rule.topic);
-                    let _ = self.near_client.unsubscribe(&rule.topic).await;
-                    let _ = self.far_client.unsubscribe(&rule.topic).await;
+            for filter in &rule.topic {
+                match rule.direction {
+                    Direction::NearToFar => {
+                        debug!("Unsubscribing from '{}' on NEAR broker", filter);
+                        let _ = self.near_client.unsubscribe(filter).await;
+                    }
+                    Direction::FarToNear => {
+                        debug!("Unsubscribing from '{}' on FAR broker", filter);
+                        let _ = self.far_client.unsubscribe(filter).await;
+                    }
+                    Direction::Wherever => {
+                        debug!("Unsubscribing from '{}' on BOTH brokers", filter);
+                        let _ = self.near_client.unsubscribe(filter).await;
+                        let _ = self.far_client.unsubscribe(filter).await;
+                    }
                 }
             }
         }
+        for rule in &old_config.rpc_rules {
+            let (near_filter, far_filter) = match rule.direction {
+                Direction::NearToFar => (&rule.command_topic, &rule.response_topic),
+                Direction::FarToNear => (&rule.response_topic, &rule.command_topic),
+                Direction::Wherever => continue,
+            };
+            debug!("Unsubscribing from RPC topic '{}' on NEAR broker", near_filter);
+            let _ = self.near_client.unsubscribe(near_filter).await;
+            debug!("Unsubscribing from RPC topic '{}' on FAR broker", far_filter);
+            let _ = self.far_client.unsubscribe(far_filter).await;
+        }
         drop(old_config);
 
+        // Forget in-flight RPC requests tracked against the old rule set
+        self.rpc_inflight.write().await.clear();
+
         // Update config
         *self.config.write().await = new_config;
 
         // Subscribe to new topics
         self.subscribe_to_topics().await?;
 
+        self.metrics.record_reload();
         info!("Configuration reloaded successfully");
         Ok(())
     }
 
-    async fn handle_near_publish(&self, publish: Publish) -> Result<()> {
+    async fn handle_near_publish(&self, publish: broker::NormalizedPublish) -> Result<()> {
         let config = self.config.read().await;
 
         // Check if this is a reload message
@@ -172,31 +250,41 @@ impl Bridge {
 
         // Find matching rules for this topic
         for rule in &config.rules {
-            if matches_topic(&rule.topic, &publish.topic) {
+            if let Some(filter) = matching_filter(rule, &publish.topic) {
                 match rule.direction {
                     Direction::NearToFar | Direction::Wherever => {
+                        let dest_topic = destination_topic(filter, rule.remap.as_deref(), &publish.topic);
                         if rule.logging {
                             info!(
-                                "NEAR→FAR: {} ({} bytes, QoS {:?})",
+                                "NEAR→FAR: {} -> {} ({} bytes, QoS {:?})",
                                 publish.topic,
+                                dest_topic,
                                 publish.payload.len(),
                                 publish.qos
                             );
                             debug!("Payload: {:?}", String::from_utf8_lossy(&publish.payload));
                         }
 
-                        self.far_client
+                        let result = self
+                            .far_client
                             .publish(
-                                &publish.topic,
+                                &dest_topic,
                                 rule.qos(),
                                 publish.retain,
                                 publish.payload.clone(),
+                                publish.properties.as_ref(),
                             )
-                            .await
-                            .context(format!(
-                                "Failed to forward message to far broker: {}",
-                                publish.topic
-                            ))?;
+                            .await;
+                        if result.is_ok() {
+                            self.metrics
+                                .record_forward("near_to_far", filter, publish.payload.len());
+                        } else {
+                            self.metrics.record_forward_failure();
+                        }
+                        result.context(format!(
+                            "Failed to forward message to far broker: {} -> {}",
+                            publish.topic, dest_topic
+                        ))?;
                     }
                     Direction::FarToNear => {
                         // Ignore messages from near when rule is FarToNear
@@ -209,10 +297,13 @@ impl Bridge {
             }
         }
 
+        self.handle_rpc_publish(&config, RpcSource::Near, &publish)
+            .await?;
+
         Ok(())
     }
 
-    async fn handle_far_publish(&self, publish: Publish) -> Result<()> {
+    async fn handle_far_publish(&self, publish: broker::NormalizedPublish) -> Result<()> {
         let config = self.config.read().await;
 
         // Check if this is a reload message
@@ -225,31 +316,41 @@ impl Bridge {
 
         // Find matching rules for this topic
         for rule in &config.rules {
-            if matches_topic(&rule.topic, &publish.topic) {
+            if let Some(filter) = matching_filter(rule, &publish.topic) {
                 match rule.direction {
                     Direction::FarToNear | Direction::Wherever => {
+                        let dest_topic = destination_topic(filter, rule.remap.as_deref(), &publish.topic);
                         if rule.logging {
                             info!(
-                                "FAR→NEAR: {} ({} bytes, QoS {:?})",
+                                "FAR→NEAR: {} -> {} ({} bytes, QoS {:?})",
                                 publish.topic,
+                                dest_topic,
                                 publish.payload.len(),
                                 publish.qos
                             );
                             debug!("Payload: {:?}", String::from_utf8_lossy(&publish.payload));
                         }
 
-                        self.near_client
+                        let result = self
+                            .near_client
                             .publish(
-                                &publish.topic,
+                                &dest_topic,
                                 rule.qos(),
                                 publish.retain,
                                 publish.payload.clone(),
+                                publish.properties.as_ref(),
                             )
-                            .await
-                            .context(format!(
-                                "Failed to forward message to near broker: {}",
-                                publish.topic
-                            ))?;
+                            .await;
+                        if result.is_ok() {
+                            self.metrics
+                                .record_forward("far_to_near", filter, publish.payload.len());
+                        } else {
+                            self.metrics.record_forward_failure();
+                        }
+                        result.context(format!(
+                            "Failed to forward message to near broker: {} -> {}",
+                            publish.topic, dest_topic
+                        ))?;
                     }
                     Direction::NearToFar => {
                         // Ignore messages from far when rule is NearToFar
@@ -262,6 +363,116 @@ impl Bridge {
             }
         }
 
+        self.handle_rpc_publish(&config, RpcSource::Far, &publish)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Forward RPC commands/responses for every `rpc_rules` entry that applies to a
+    /// publish received on `source`, deduplicating in-flight commands by correlation key.
+    async fn handle_rpc_publish(
+        &self,
+        config: &BridgeConfig,
+        source: RpcSource,
+        publish: &broker::NormalizedPublish,
+    ) -> Result<()> {
+        for (i, rule) in config.rpc_rules.iter().enumerate() {
+            let Some(role) = rpc_role(rule.direction, source) else {
+                continue;
+            };
+
+            match role {
+                RpcRole::Command if matches_topic(&rule.command_topic, &publish.topic) => {
+                    let dest = match source {
+                        RpcSource::Near => &self.far_client,
+                        RpcSource::Far => &self.near_client,
+                    };
+
+                    let key = rpc_key(rule, publish);
+                    if let Some(key) = &key {
+                        let mut inflight = self.rpc_inflight.write().await;
+                        prune_expired(&mut inflight, i, Duration::from_secs(rule.timeout_secs));
+                        if inflight.contains_key(&(i, key.clone())) {
+                            warn!(
+                                "Duplicate RPC request '{}' for '{}' while a prior one is still in-flight; not forwarding again",
+                                key, publish.topic
+                            );
+                            continue;
+                        }
+                    }
+
+                    debug!("RPC command: forwarding '{}'", publish.topic);
+                    let result = dest
+                        .publish(
+                            &publish.topic,
+                            rule.qos(),
+                            publish.retain,
+                            publish.payload.clone(),
+                            publish.properties.as_ref(),
+                        )
+                        .await;
+                    if result.is_ok() {
+                        // Only track the request as in-flight once it's actually been
+                        // forwarded — otherwise a failed forward would still block every
+                        // retry for this key until it expires.
+                        if let Some(key) = key {
+                            self.rpc_inflight
+                                .write()
+                                .await
+                                .insert((i, key), Instant::now());
+                        }
+                        self.metrics.record_forward(
+                            "rpc_command",
+                            &rule.command_topic,
+                            publish.payload.len(),
+                        );
+                    } else {
+                        self.metrics.record_forward_failure();
+                    }
+                    result.context(format!("Failed to forward RPC command: {}", publish.topic))?;
+                }
+                RpcRole::Response if matches_topic(&rule.response_topic, &publish.topic) => {
+                    let dest = match source {
+                        RpcSource::Near => &self.far_client,
+                        RpcSource::Far => &self.near_client,
+                    };
+
+                    if let Some(key) = rpc_key(rule, publish) {
+                        let removed = self.rpc_inflight.write().await.remove(&(i, key.clone()));
+                        if removed.is_none() {
+                            debug!(
+                                "RPC response '{}' for '{}' has no matching in-flight request; forwarding anyway",
+                                key, publish.topic
+                            );
+                        }
+                    }
+
+                    debug!("RPC response: forwarding '{}'", publish.topic);
+                    let result = dest
+                        .publish(
+                            &publish.topic,
+                            rule.qos(),
+                            publish.retain,
+                            publish.payload.clone(),
+                            publish.properties.as_ref(),
+                        )
+                        .await;
+                    if result.is_ok() {
+                        self.metrics.record_forward(
+                            "rpc_response",
+                            &rule.response_topic,
+                            publish.payload.len(),
+                        );
+                    } else {
+                        self.metrics.record_forward_failure();
+                    }
+                    result.context(format!("Failed to forward RPC response: {}", publish.topic))?;
+                }
+                _ => {}
+            }
+        }
+
         Ok(())
     }
 
@@ -277,38 +488,44 @@ impl Bridge {
             tokio::select! {
                 event = self.near_eventloop.poll() => {
                     match event {
-                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        Ok(BridgeEvent::Publish(publish)) => {
                             if let Err(e) = self.handle_near_publish(publish).await {
                                 error!("Error handling NEAR publish: {:#}", e);
                             }
                         }
-                        Ok(Event::Incoming(packet)) => {
-                            debug!("NEAR incoming: {:?}", packet);
-                        }
-                        Ok(Event::Outgoing(_)) => {
-                            // Ignore outgoing events
+                        Ok(BridgeEvent::ConnAck) => {
+                            info!("NEAR broker (re)connected, re-issuing subscriptions");
+                            self.metrics.set_connected("near", true);
+                            if let Err(e) = self.subscribe_near_topics().await {
+                                error!("Error re-subscribing on NEAR broker: {:#}", e);
+                            }
                         }
+                        Ok(BridgeEvent::Other) => {}
                         Err(e) => {
-                            error!("NEAR connection error: {}", e);
+                            error!("NEAR connection error: {:#}", e);
+                            self.metrics.set_connected("near", false);
                             tokio::time::sleep(Duration::from_secs(5)).await;
                         }
                     }
                 }
                 event = self.far_eventloop.poll() => {
                     match event {
-                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        Ok(BridgeEvent::Publish(publish)) => {
                             if let Err(e) = self.handle_far_publish(publish).await {
                                 error!("Error handling FAR publish: {:#}", e);
                             }
                         }
-                        Ok(Event::Incoming(packet)) => {
-                            debug!("FAR incoming: {:?}", packet);
-                        }
-                        Ok(Event::Outgoing(_)) => {
-                            // Ignore outgoing events
+                        Ok(BridgeEvent::ConnAck) => {
+                            info!("FAR broker (re)connected, re-issuing subscriptions");
+                            self.metrics.set_connected("far", true);
+                            if let Err(e) = self.subscribe_far_topics().await {
+                                error!("Error re-subscribing on FAR broker: {:#}", e);
+                            }
                         }
+                        Ok(BridgeEvent::Other) => {}
                         Err(e) => {
-                            error!("FAR connection error: {}", e);
+                            error!("FAR connection error: {:#}", e);
+                            self.metrics.set_connected("far", false);
                             tokio::time::sleep(Duration::from_secs(5)).await;
                         }
                     }
@@ -318,44 +535,309 @@ impl Bridge {
     }
 }
 
-fn create_mqtt_client(config: &MqttBrokerConfig, name: &str) -> Result<(AsyncClient, EventLoop)> {
-    let mut mqttoptions = MqttOptions::new(&config.client_id, &config.host, config.port);
+fn create_mqtt_client(
+    config: &MqttBrokerConfig,
+    name: &str,
+) -> Result<(ClientHandle, EventLoopHandle)> {
+    info!(
+        "Creating {} MQTT client: {}:{} (id: {}, protocol: {:?})",
+        name, config.host, config.port, config.client_id, config.protocol
+    );
+
+    match config.protocol {
+        MqttProtocol::V4 => {
+            let mut mqttoptions = MqttOptions::new(&config.client_id, &config.host, config.port);
+
+            if let (Some(username), Some(password)) = (&config.username, &config.password) {
+                mqttoptions.set_credentials(username, password);
+            }
+
+            if let Some(tls) = &config.tls {
+                mqttoptions.set_transport(Transport::Tls(build_tls_config(tls, name)?));
+            }
 
-    if let (Some(username), Some(password)) = (&config.username, &config.password) {
-        mqttoptions.set_credentials(username, password);
+            mqttoptions.set_keep_alive(Duration::from_secs(30));
+
+            Ok(broker::new_v4(mqttoptions))
+        }
+        MqttProtocol::V5 => {
+            let mut mqttoptions = MqttOptionsV5::new(&config.client_id, &config.host, config.port);
+
+            if let (Some(username), Some(password)) = (&config.username, &config.password) {
+                mqttoptions.set_credentials(username, password);
+            }
+
+            if let Some(tls) = &config.tls {
+                mqttoptions.set_transport(Transport::Tls(build_tls_config(tls, name)?));
+            }
+
+            mqttoptions.set_keep_alive(Duration::from_secs(30));
+
+            Ok(broker::new_v5(mqttoptions))
+        }
     }
+}
 
-    mqttoptions.set_keep_alive(Duration::from_secs(30));
+/// Build rumqttc's TLS transport from a `TlsConfig`, validating that every referenced
+/// PEM file exists and parses now rather than letting it surface as a connection error.
+fn build_tls_config(tls: &TlsConfig, name: &str) -> Result<TlsConfiguration> {
+    let ca = std::fs::read(&tls.ca_cert)
+        .with_context(|| format!("Failed to read {} CA certificate at {:?}", name, tls.ca_cert))?;
+    validate_pem_certs(&ca, name, "CA certificate")?;
+
+    let client_auth = match (&tls.client_cert, &tls.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = std::fs::read(cert_path).with_context(|| {
+                format!("Failed to read {} client certificate at {:?}", name, cert_path)
+            })?;
+            validate_pem_certs(&cert, name, "client certificate")?;
+            let key = std::fs::read(key_path)
+                .with_context(|| format!("Failed to read {} client key at {:?}", name, key_path))?;
+            // Parsed eagerly (and discarded here) purely so a malformed key fails at
+            // startup; rumqttc re-parses the raw PEM bytes itself once it connects.
+            parse_private_key(&key, name)?;
+            Some((cert, key))
+        }
+        (None, None) => None,
+        _ => bail!(
+            "{} broker TLS config must set both client_cert and client_key, or neither",
+            name
+        ),
+    };
+
+    if !tls.insecure_skip_verify {
+        return Ok(TlsConfiguration::Simple {
+            ca,
+            alpn: None,
+            client_auth,
+        });
+    }
 
-    info!(
-        "Creating {} MQTT client: {}:{} (id: {})",
-        name, config.host, config.port, config.client_id
+    warn!(
+        "{} broker TLS verification is DISABLED (insecure_skip_verify = true); do not use this in production",
+        name
     );
 
-    Ok(AsyncClient::new(mqttoptions, 100))
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut ca.as_slice())
+        .with_context(|| format!("Failed to parse {} CA certificate", name))?
+    {
+        roots
+            .add(&rustls::Certificate(cert))
+            .with_context(|| format!("Failed to add {} CA certificate to root store", name))?;
+    }
+
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+
+    let mut client_config = match client_auth {
+        Some((cert, key)) => {
+            let certs = rustls_pemfile::certs(&mut cert.as_slice())
+                .context("Failed to parse client certificate")?
+                .into_iter()
+                .map(rustls::Certificate)
+                .collect();
+            let key = parse_private_key(&key, name)?;
+            builder
+                .with_client_auth_cert(certs, rustls::PrivateKey(key))
+                .context("Invalid client certificate/key pair")?
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    client_config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(NoServerVerification));
+
+    Ok(TlsConfiguration::Rustls(Arc::new(client_config)))
+}
+
+/// Check that a PEM blob parses as at least one X.509 certificate, without keeping the
+/// parsed result around — `TlsConfiguration::Simple` wants the original raw PEM bytes.
+fn validate_pem_certs(pem: &[u8], name: &str, what: &str) -> Result<()> {
+    let certs = rustls_pemfile::certs(&mut &*pem)
+        .with_context(|| format!("Failed to parse {} {}", name, what))?;
+    if certs.is_empty() {
+        bail!("{} {} contained no certificates", name, what);
+    }
+    Ok(())
+}
+
+/// Parse a client private key PEM, accepting PKCS#8, PKCS#1 (RSA), or SEC1 (EC) encoding,
+/// since certs minted by different CAs/tools commonly land in any of the three.
+fn parse_private_key(key_pem: &[u8], name: &str) -> Result<Vec<u8>> {
+    if let Some(key) = rustls_pemfile::pkcs8_private_keys(&mut &*key_pem)
+        .ok()
+        .and_then(|mut keys| keys.pop())
+    {
+        return Ok(key);
+    }
+    if let Some(key) = rustls_pemfile::rsa_private_keys(&mut &*key_pem)
+        .ok()
+        .and_then(|mut keys| keys.pop())
+    {
+        return Ok(key);
+    }
+    if let Some(key) = rustls_pemfile::ec_private_keys(&mut &*key_pem)
+        .ok()
+        .and_then(|mut keys| keys.pop())
+    {
+        return Ok(key);
+    }
+    bail!(
+        "{} client key is not a valid PKCS#8, PKCS#1 (RSA), or SEC1 (EC) private key",
+        name
+    )
+}
+
+/// A `rustls` verifier that accepts any server certificate, backing `insecure_skip_verify`.
+struct NoServerVerification;
+
+impl ServerCertVerifier for NoServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
 }
 
 /// Check if a message topic matches a subscription topic (with wildcards)
 fn matches_topic(subscription: &str, topic: &str) -> bool {
+    capture_topic(subscription, topic).is_some()
+}
+
+/// Match a topic against a subscription filter, returning the segment(s) captured by
+/// each wildcard in `subscription`, in order. A `#` capture is the whole remaining
+/// suffix, joined back together with `/`. Returns `None` if the topic doesn't match.
+fn capture_topic(subscription: &str, topic: &str) -> Option<Vec<String>> {
     let sub_parts: Vec<&str> = subscription.split('/').collect();
     let topic_parts: Vec<&str> = topic.split('/').collect();
+    let mut captures = Vec::new();
 
     if sub_parts.last() == Some(&"#") {
         // Multi-level wildcard
         let sub_prefix = &sub_parts[..sub_parts.len() - 1];
-        topic_parts.len() >= sub_prefix.len()
-            && sub_prefix
-                .iter()
-                .zip(topic_parts.iter())
-                .all(|(s, t)| *s == "+" || *s == *t)
+        if topic_parts.len() < sub_prefix.len() {
+            return None;
+        }
+        for (s, t) in sub_prefix.iter().zip(topic_parts.iter()) {
+            if *s == "+" {
+                captures.push((*t).to_string());
+            } else if *s != *t {
+                return None;
+            }
+        }
+        captures.push(topic_parts[sub_prefix.len()..].join("/"));
     } else {
         // Single-level wildcards or exact match
-        sub_parts.len() == topic_parts.len()
-            && sub_parts
+        if sub_parts.len() != topic_parts.len() {
+            return None;
+        }
+        for (s, t) in sub_parts.iter().zip(topic_parts.iter()) {
+            if *s == "+" {
+                captures.push((*t).to_string());
+            } else if *s != *t {
+                return None;
+            }
+        }
+    }
+
+    Some(captures)
+}
+
+/// Apply a rule's `remap` template, substituting each `+`/`#` placeholder in order
+/// with the corresponding segment captured from the matched topic.
+fn remap_topic(remap: &str, captures: &[String]) -> String {
+    let mut captures = captures.iter();
+    remap
+        .split('/')
+        .map(|segment| match segment {
+            "+" | "#" => captures.next().cloned().unwrap_or_default(),
+            literal => literal.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Return the first filter in `rule.topic` that matches `topic`, if any.
+fn matching_filter<'a>(rule: &'a mr_bridge::BridgeRule, topic: &str) -> Option<&'a str> {
+    rule.topic
+        .iter()
+        .find(|filter| matches_topic(filter, topic))
+        .map(String::as_str)
+}
+
+/// Compute the topic a matched rule should publish on, applying `remap` (if set)
+/// against the wildcard segments `filter` captured from `topic`.
+fn destination_topic(filter: &str, remap: Option<&str>, topic: &str) -> String {
+    match (remap, capture_topic(filter, topic)) {
+        (Some(remap), Some(captures)) => remap_topic(remap, &captures),
+        _ => topic.to_string(),
+    }
+}
+
+/// Which broker a publish was received on, for `Bridge::handle_rpc_publish`.
+#[derive(Debug, Clone, Copy)]
+enum RpcSource {
+    Near,
+    Far,
+}
+
+/// Whether a publish received on `source` plays the command or response role for an
+/// `RpcRule` with the given `direction`. `None` if this rule doesn't apply to `source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RpcRole {
+    Command,
+    Response,
+}
+
+fn rpc_role(direction: Direction, source: RpcSource) -> Option<RpcRole> {
+    match (direction, source) {
+        (Direction::NearToFar, RpcSource::Near) => Some(RpcRole::Command),
+        (Direction::NearToFar, RpcSource::Far) => Some(RpcRole::Response),
+        (Direction::FarToNear, RpcSource::Far) => Some(RpcRole::Command),
+        (Direction::FarToNear, RpcSource::Near) => Some(RpcRole::Response),
+        (Direction::Wherever, _) => None,
+    }
+}
+
+/// Derive the correlation key for an RPC message: the v5 correlation-data if present,
+/// otherwise the configured topic segment. Returns `None` when neither is available,
+/// meaning this message can't be deduplicated/correlated.
+fn rpc_key(rule: &RpcRule, publish: &broker::NormalizedPublish) -> Option<String> {
+    if let Some(correlation_data) = publish
+        .properties
+        .as_ref()
+        .and_then(|props| props.correlation_data.as_ref())
+    {
+        return Some(
+            correlation_data
                 .iter()
-                .zip(topic_parts.iter())
-                .all(|(s, t)| *s == "+" || *s == *t)
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        );
     }
+
+    let segment = rule.request_id_segment?;
+    publish.topic.split('/').nth(segment).map(str::to_string)
+}
+
+/// Drop in-flight entries for rule `rule_index` older than `timeout`, so a request whose
+/// response never arrives doesn't permanently block future requests with the same key.
+fn prune_expired(
+    inflight: &mut HashMap<(usize, String), Instant>,
+    rule_index: usize,
+    timeout: Duration,
+) {
+    let now = Instant::now();
+    inflight.retain(|(idx, _), inserted| *idx != rule_index || now.duration_since(*inserted) < timeout);
 }
 
 #[tokio::main]
@@ -414,4 +896,66 @@ mod tests {
         ));
         assert!(!matches_topic("home/+/sensor/#", "home/living/other/temp"));
     }
+
+    #[test]
+    fn test_topic_remap() {
+        // Exact topic, no wildcards to capture
+        assert_eq!(
+            remap_topic("upstream/sensors", &[]),
+            "upstream/sensors".to_string()
+        );
+
+        // Single-level wildcard captured and reused in the remap
+        let captures = capture_topic("sensors/+/temp", "sensors/living/temp").unwrap();
+        assert_eq!(
+            remap_topic("upstream/sensors/+/temp", &captures),
+            "upstream/sensors/living/temp"
+        );
+
+        // Multi-level wildcard captures the whole remaining suffix
+        let captures = capture_topic("sensors/#", "sensors/living/temp").unwrap();
+        assert_eq!(remap_topic("upstream/#", &captures), "upstream/living/temp");
+
+        assert!(capture_topic("sensors/+/temp", "sensors/living/room/temp").is_none());
+    }
+
+    fn test_rpc_rule() -> RpcRule {
+        RpcRule {
+            command_topic: "devices/+/command/#".to_string(),
+            response_topic: "devices/+/response/#".to_string(),
+            direction: Direction::NearToFar,
+            request_id_segment: Some(1),
+            timeout_secs: 30,
+            qos: 0,
+        }
+    }
+
+    #[test]
+    fn test_rpc_key_falls_back_to_topic_segment_without_v5_properties() {
+        let rule = test_rpc_rule();
+        let publish = broker::NormalizedPublish {
+            topic: "devices/device-1/command/reboot".to_string(),
+            payload: vec![],
+            qos: QoS::AtMostOnce,
+            retain: false,
+            properties: None,
+        };
+
+        assert_eq!(rpc_key(&rule, &publish), Some("device-1".to_string()));
+    }
+
+    #[test]
+    fn test_prune_expired_only_drops_stale_entries_for_the_given_rule() {
+        let mut inflight: HashMap<(usize, String), Instant> = HashMap::new();
+        inflight.insert((0, "a".to_string()), Instant::now() - Duration::from_secs(60));
+        inflight.insert((0, "b".to_string()), Instant::now());
+        inflight.insert((1, "a".to_string()), Instant::now() - Duration::from_secs(60));
+
+        prune_expired(&mut inflight, 0, Duration::from_secs(30));
+
+        assert!(!inflight.contains_key(&(0, "a".to_string())));
+        assert!(inflight.contains_key(&(0, "b".to_string())));
+        // Rule 1 wasn't pruned, so its stale entry should be untouched.
+        assert!(inflight.contains_key(&(1, "a".to_string())));
+    }
 }
@@ -0,0 +1,150 @@
+//! A tiny Prometheus text-format metrics endpoint. No HTTP framework dependency: every
+//! connection gets the current snapshot regardless of method/path, which is all a
+//! scrape target needs.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+#[derive(Default, Clone, Copy)]
+struct ForwardCounter {
+    messages: u64,
+    bytes: u64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    forwarded: Mutex<HashMap<(&'static str, String), ForwardCounter>>,
+    near_up: AtomicBool,
+    far_up: AtomicBool,
+    reload_count: AtomicU64,
+    forward_failures: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_forward(&self, direction: &'static str, rule_topic: &str, bytes: usize) {
+        let mut forwarded = self.forwarded.lock().unwrap();
+        let counter = forwarded
+            .entry((direction, rule_topic.to_string()))
+            .or_default();
+        counter.messages += 1;
+        counter.bytes += bytes as u64;
+    }
+
+    pub fn record_forward_failure(&self) {
+        self.forward_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_reload(&self) {
+        self.reload_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_connected(&self, broker: &str, up: bool) {
+        match broker {
+            "near" => self.near_up.store(up, Ordering::Relaxed),
+            "far" => self.far_up.store(up, Ordering::Relaxed),
+            _ => {}
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP mr_bridge_messages_forwarded_total Messages forwarded across the bridge\n",
+        );
+        out.push_str("# TYPE mr_bridge_messages_forwarded_total counter\n");
+        out.push_str(
+            "# HELP mr_bridge_bytes_forwarded_total Payload bytes forwarded across the bridge\n",
+        );
+        out.push_str("# TYPE mr_bridge_bytes_forwarded_total counter\n");
+        {
+            let forwarded = self.forwarded.lock().unwrap();
+            for ((direction, rule_topic), counter) in forwarded.iter() {
+                out.push_str(&format!(
+                    "mr_bridge_messages_forwarded_total{{direction=\"{direction}\",rule_topic=\"{rule_topic}\"}} {}\n",
+                    counter.messages
+                ));
+            }
+            for ((direction, rule_topic), counter) in forwarded.iter() {
+                out.push_str(&format!(
+                    "mr_bridge_bytes_forwarded_total{{direction=\"{direction}\",rule_topic=\"{rule_topic}\"}} {}\n",
+                    counter.bytes
+                ));
+            }
+        }
+
+        out.push_str(
+            "# HELP mr_bridge_broker_up Whether the bridge is currently connected to a broker\n",
+        );
+        out.push_str("# TYPE mr_bridge_broker_up gauge\n");
+        out.push_str(&format!(
+            "mr_bridge_broker_up{{broker=\"near\"}} {}\n",
+            self.near_up.load(Ordering::Relaxed) as u8
+        ));
+        out.push_str(&format!(
+            "mr_bridge_broker_up{{broker=\"far\"}} {}\n",
+            self.far_up.load(Ordering::Relaxed) as u8
+        ));
+
+        out.push_str("# HELP mr_bridge_reload_total Number of configuration reloads\n");
+        out.push_str("# TYPE mr_bridge_reload_total counter\n");
+        out.push_str(&format!(
+            "mr_bridge_reload_total {}\n",
+            self.reload_count.load(Ordering::Relaxed)
+        ));
+
+        out.push_str(
+            "# HELP mr_bridge_forward_failures_total Number of messages that failed to forward\n",
+        );
+        out.push_str("# TYPE mr_bridge_forward_failures_total counter\n");
+        out.push_str(&format!(
+            "mr_bridge_forward_failures_total {}\n",
+            self.forward_failures.load(Ordering::Relaxed)
+        ));
+
+        out
+    }
+}
+
+/// Serve the metrics snapshot over plain HTTP at `listen` until the process exits.
+pub async fn serve(listen: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to bind metrics listener on {listen}"))?;
+
+    info!("Metrics endpoint listening on {}", listen);
+
+    loop {
+        let (mut socket, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("Metrics listener accept error: {}", e);
+                continue;
+            }
+        };
+        let metrics = metrics.clone();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            // We don't parse the request; any connection just wants the snapshot.
+            let _ = socket.read(&mut buf).await;
+
+            let body = metrics.render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+
+            if let Err(e) = socket.write_all(response.as_bytes()).await {
+                error!("Failed to write metrics response: {}", e);
+            }
+        });
+    }
+}